@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use rlua::Lua;
+
+use crate::config::Config;
+use crate::templating::{self, TemplateCache, TemplateContext};
+
+// Placeholder until `opt` parses a template path from CLI args; both
+// commands render the same file for now.
+const TEMPLATE_PATH: &str = "template.tpl";
+
+pub(crate) fn generate() -> Result<()> {
+    render(&Config::default())
+}
+
+pub(crate) fn run() -> Result<()> {
+    render(&Config::default())
+}
+
+// Parses and renders the configured template, consulting `config.cache_dir`
+// to opt into `Template::generate_cached` instead of a plain `generate`, and
+// `config.context_path` to inject a `TemplateContext` loaded from disk
+// before rendering.
+fn render(config: &Config) -> Result<()> {
+    let path = Path::new(TEMPLATE_PATH);
+    let raw = fs::read_to_string(path)?;
+    let template = templating::parse_template(&raw)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let context = config
+        .context_path
+        .as_deref()
+        .map(TemplateContext::load)
+        .transpose()?;
+    // Folded into the cache key so editing the context file busts a cached
+    // render the same way editing the template source or an included file
+    // does (see `Cached::fingerprint`).
+    let context_input = context.as_ref().map(|c| format!("{:?}", c));
+    let inputs: Vec<&str> = context_input.as_deref().into_iter().collect();
+
+    let lua = Lua::new();
+    let output = templating::with_base_dir(base_dir, || {
+        lua.context(|lua_context| {
+            if let Some(context) = &context {
+                context.inject(&lua_context)?;
+            }
+            match &config.cache_dir {
+                Some(dir) => {
+                    template.generate_cached(&lua_context, &TemplateCache::new(dir), &inputs, base_dir)
+                }
+                None => template.generate(&lua_context),
+            }
+        })
+    })?;
+
+    println!("{}", output);
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+// Process-wide knobs threaded into `commands::generate`/`commands::run`.
+// Currently just the template-cache and data-context opt-ins; real values
+// will come from CLI flags once `opt` parses them into this struct.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Config {
+    // When set, rendered output is cached on disk under this directory,
+    // keyed by `templating::Cached::fingerprint`. `None` disables caching.
+    pub cache_dir: Option<PathBuf>,
+    // When set, a `.json`/`.toml` file loaded via `TemplateContext::load`
+    // and injected as Lua globals before rendering. `None` renders with
+    // whatever globals the template's own directives populate.
+    pub context_path: Option<PathBuf>,
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha512};
+
+// Disk-backed store for previously rendered template output, keyed by a
+// SHA-512 fingerprint of the template source plus whatever Lua-visible
+// inputs shaped the render. Opting in is the caller's job: pass a
+// `TemplateCache` to `Template::generate_cached` and a miss falls back to a
+// plain `generate` whose result is persisted for next time; `generate`
+// itself never touches the cache, so uncached callers pay nothing.
+#[derive(Debug, Clone)]
+pub(crate) struct TemplateCache {
+    dir: PathBuf,
+}
+
+impl TemplateCache {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub(crate) fn get(&self, fingerprint: &str) -> Option<String> {
+        fs::read_to_string(self.entry_path(fingerprint)).ok()
+    }
+
+    pub(crate) fn put(&self, fingerprint: &str, rendered: &str) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.entry_path(fingerprint), rendered)
+    }
+
+    fn entry_path(&self, fingerprint: &str) -> PathBuf {
+        self.dir.join(fingerprint)
+    }
+}
+
+// Implemented by anything a `TemplateCache` can key on. `inputs` is whatever
+// Lua-visible values affected the render (e.g. the serialized globals a
+// caller is about to inject); passing them through the digest means editing
+// either the template source or its inputs busts the cache. `base_dir` is
+// the directory `include` paths resolve against, matching how `Include`
+// itself resolves them at render time (see `directive::Include::generate`),
+// so the digest also covers whatever the template transitively includes.
+pub(crate) trait Cached {
+    fn fingerprint(&self, inputs: &[&str], base_dir: &Path) -> String;
+}
+
+impl Cached for super::template::Template {
+    fn fingerprint(&self, inputs: &[&str], base_dir: &Path) -> String {
+        let mut hasher = Sha512::new();
+        hasher.update(self.source.as_bytes());
+        let mut visited = HashSet::new();
+        for path in self.include_paths() {
+            hash_include(&mut hasher, base_dir, &path, &mut visited);
+        }
+        for input in inputs {
+            hasher.update(b"\0");
+            hasher.update(input.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+// Folds the content of `path` (resolved against `base_dir`), and anything
+// it transitively includes, into `hasher`, so editing a nested partial
+// busts every ancestor's fingerprint too. An unreadable or unparsable
+// include is skipped rather than failing the whole fingerprint — the
+// render itself will surface the real error.
+//
+// `visited` mirrors `directive::INCLUDE_STACK`: it tracks canonicalized
+// paths already folded into this fingerprint, so an include cycle (`a`
+// includes `b` includes `a`) stops recursing here instead of overflowing
+// the stack the way `Include::generate` is built to reject at render time.
+fn hash_include(hasher: &mut Sha512, base_dir: &Path, path: &str, visited: &mut HashSet<PathBuf>) {
+    let resolved = base_dir.join(path);
+    let canonical = fs::canonicalize(&resolved).unwrap_or(resolved);
+    if !visited.insert(canonical.clone()) {
+        return;
+    }
+
+    let raw = match fs::read_to_string(&canonical) {
+        Ok(raw) => raw,
+        Err(_) => return,
+    };
+    hasher.update(b"\0");
+    hasher.update(raw.as_bytes());
+
+    if let Ok(template) = super::parser::parse_template(&raw) {
+        let nested_base = canonical.parent().unwrap_or(base_dir);
+        for nested_path in template.include_paths() {
+            hash_include(hasher, nested_base, &nested_path, visited);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn unique_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("templar-cache-test-{}-{}-{}", std::process::id(), name, n));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fingerprint_does_not_recurse_forever_on_include_cycle() {
+        let dir = unique_dir("cycle");
+        fs::write(dir.join("a.tpl"), "!!% include b.tpl \n%!!").unwrap();
+        fs::write(dir.join("b.tpl"), "!!% include a.tpl \n%!!").unwrap();
+
+        let raw = fs::read_to_string(dir.join("a.tpl")).unwrap();
+        let template = super::super::parser::parse_template(&raw).unwrap();
+
+        // Would stack-overflow and abort the process before this returns if
+        // `hash_include` didn't guard against revisiting `a.tpl`.
+        let digest = template.fingerprint(&[], &dir);
+        assert_eq!(digest.len(), 128);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_get_misses_until_a_matching_put() {
+        let dir = unique_dir("store");
+        let cache = TemplateCache::new(&dir);
+
+        assert_eq!(cache.get("deadbeef"), None);
+        cache.put("deadbeef", "rendered output").unwrap();
+        assert_eq!(cache.get("deadbeef").as_deref(), Some("rendered output"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_template_source() {
+        let dir = unique_dir("source");
+        let a = super::super::parser::parse_template("hello").unwrap();
+        let b = super::super::parser::parse_template("goodbye").unwrap();
+
+        assert_ne!(a.fingerprint(&[], &dir), b.fingerprint(&[], &dir));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_inputs() {
+        let dir = unique_dir("inputs");
+        let template = super::super::parser::parse_template("hello").unwrap();
+
+        assert_ne!(
+            template.fingerprint(&["a"], &dir),
+            template.fingerprint(&["b"], &dir)
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fingerprint_changes_when_an_included_file_changes() {
+        let dir = unique_dir("include-invalidation");
+        fs::write(dir.join("partial.tpl"), "v1").unwrap();
+        let template = super::super::parser::parse_template("!!% include partial.tpl \n%!!").unwrap();
+        let before = template.fingerprint(&[], &dir);
+
+        fs::write(dir.join("partial.tpl"), "v2").unwrap();
+        let after = template.fingerprint(&[], &dir);
+
+        assert_ne!(before, after);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use rlua::prelude::*;
+
+// A JSON/TOML-shaped value a caller supplies as template input, independent
+// of any Lua type. A tree of these rooted at `TemplateContext` is what gets
+// injected into Lua globals before rendering, so a user never has to
+// hand-populate `lua_context.globals()` themselves the way `Transform` and
+// `ForEach` do for their own scoped bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    List(Vec<Value>),
+    Map(HashMap<String, Value>),
+}
+
+impl Value {
+    // Walks a dotted path (`address.city`) through nested `Map`/`List`
+    // values. List segments are plain non-negative indices (`items.0`).
+    pub(crate) fn get(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, segment| match value {
+            Value::Map(fields) => fields.get(segment),
+            Value::List(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+    }
+
+    fn to_lua<'lua>(&self, lua_context: LuaContext<'lua>) -> LuaResult<LuaValue<'lua>> {
+        Ok(match self {
+            Value::String(s) => LuaValue::String(lua_context.create_string(s)?),
+            Value::Integer(i) => LuaValue::Integer(*i),
+            Value::Bool(b) => LuaValue::Boolean(*b),
+            Value::List(items) => {
+                let table = lua_context.create_table()?;
+                for (i, item) in items.iter().enumerate() {
+                    table.set(i + 1, item.to_lua(lua_context)?)?;
+                }
+                LuaValue::Table(table)
+            }
+            Value::Map(fields) => {
+                let table = lua_context.create_table()?;
+                for (key, value) in fields {
+                    table.set(key.clone(), value.to_lua(lua_context)?)?;
+                }
+                LuaValue::Table(table)
+            }
+        })
+    }
+}
+
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Integer(i),
+                None => Value::String(n.to_string()),
+            },
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Array(items) => {
+                Value::List(items.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(fields) => Value::Map(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+            ),
+            serde_json::Value::Null => Value::Map(HashMap::new()),
+        }
+    }
+}
+
+impl From<toml::Value> for Value {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Integer(i) => Value::Integer(i),
+            toml::Value::Float(f) => Value::String(f.to_string()),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(d) => Value::String(d.to_string()),
+            toml::Value::Array(items) => {
+                Value::List(items.into_iter().map(Value::from).collect())
+            }
+            toml::Value::Table(fields) => Value::Map(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// Root of a template's structured input data: a named set of `Value`s a
+// caller builds up from JSON/TOML and hands to `Template::generate_with_context`
+// instead of populating `lua_context.globals()` imperatively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TemplateContext(HashMap<String, Value>);
+
+impl TemplateContext {
+    pub(crate) fn new(fields: HashMap<String, Value>) -> Self {
+        Self(fields)
+    }
+
+    // Loads a context from a `.json` or `.toml` file, so `config::Config`
+    // can point at a plain data file instead of requiring callers to build
+    // a `TemplateContext` by hand. The extension picks the format; anything
+    // else is an error rather than a silent guess.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read context file `{}`", path.display()))?;
+        let fields = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => match serde_json::from_str(&raw)
+                .with_context(|| format!("failed to parse context file `{}`", path.display()))?
+            {
+                serde_json::Value::Object(fields) => fields
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+                _ => anyhow::bail!("context file `{}` must be a JSON object", path.display()),
+            },
+            Some("toml") => match raw
+                .parse::<toml::Value>()
+                .with_context(|| format!("failed to parse context file `{}`", path.display()))?
+            {
+                toml::Value::Table(fields) => fields
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+                _ => anyhow::bail!("context file `{}` must be a TOML table", path.display()),
+            },
+            other => anyhow::bail!(
+                "context file `{}` has unsupported extension {:?} (expected `.json` or `.toml`)",
+                path.display(),
+                other
+            ),
+        };
+        Ok(Self(fields))
+    }
+
+    // Resolves a dotted path (`user.address.city`) from the root, so both
+    // plain Rust code and convenience directives can look a value up without
+    // hand-written Lua table indexing.
+    pub(crate) fn get(&self, path: &str) -> Option<&Value> {
+        let mut segments = path.splitn(2, '.');
+        let value = self.0.get(segments.next()?)?;
+        match segments.next() {
+            Some(rest) => value.get(rest),
+            None => Some(value),
+        }
+    }
+
+    // Sets every top-level field as a Lua global, so directive conditions,
+    // transforms and `foreach` collections can reference it directly (e.g.
+    // `!!% if user.active` once the `user` map has been injected).
+    pub(crate) fn inject(&self, lua_context: &LuaContext) -> Result<()> {
+        for (key, value) in &self.0 {
+            lua_context
+                .globals()
+                .set(key.clone(), value.to_lua(*lua_context)?)?;
+        }
+        Ok(())
+    }
+}
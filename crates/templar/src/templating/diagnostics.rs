@@ -0,0 +1,75 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use ariadne::{Label, Report, ReportKind, Source};
+use nom_locate::LocatedSpan;
+
+// Input type the parser runs over: a `&str` paired with its byte offset and
+// line/column, plus a cheap clone (`Rc<str>`) of the whole source text so a
+// span captured deep in the parse tree can still render a diagnostic against
+// it later. See `SourceSpan::of`.
+pub(super) type Span<'a> = LocatedSpan<&'a str, Rc<str>>;
+
+// The source location of a directive, captured once at parse time and
+// carried on the directive struct for the rest of its life. When generating
+// fails (a bad Lua condition/transform, an include cycle, ...) this is what
+// lets the error point back at the exact template line instead of just
+// repeating the Lua/anyhow error text.
+#[derive(Debug, Clone)]
+pub(super) struct SourceSpan {
+    source: Rc<str>,
+    range: Range<usize>,
+    line: u32,
+    column: u32,
+}
+
+impl SourceSpan {
+    // Spans the header line of the directive starting at `span`, e.g. the
+    // `!!% if x > 1` line of an `if` directive.
+    pub(super) fn of(span: &Span) -> Self {
+        let start = span.location_offset();
+        let len = span.fragment().lines().next().unwrap_or("").len();
+        Self {
+            source: span.extra.clone(),
+            range: start..start + len,
+            line: span.location_line(),
+            column: span.get_utf8_column() as u32,
+        }
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+// Wraps an evaluation error in an ariadne report that underlines the
+// directive's source line, e.g.:
+//
+//   Error: attempt to compare nil with number
+//      ╭─[ 4:1 ]
+//      │
+//    4 │ !!% if x > 1
+//      │ ───────────── attempt to compare nil with number
+//   ───╯
+//
+// Falls back to a plain "line N, column M: <err>" string if rendering the
+// report itself goes wrong, so a diagnostics bug never hides the real error.
+pub(super) fn annotate<E: fmt::Display>(span: &SourceSpan, err: E) -> anyhow::Error {
+    let message = err.to_string();
+    let mut buf = Vec::new();
+    let rendered = Report::build(ReportKind::Error, (), span.range.start)
+        .with_message(&message)
+        .with_label(Label::new(span.range.clone()).with_message(&message))
+        .finish()
+        .write(Source::from(span.source.as_ref()), &mut buf)
+        .ok()
+        .and_then(|()| String::from_utf8(buf).ok());
+
+    match rendered {
+        Some(report) => anyhow::anyhow!(report),
+        None => anyhow::anyhow!("{}: {}", span, message),
+    }
+}
@@ -1,15 +1,25 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rlua::prelude::*;
-use std::fmt::Debug;
 
-use super::{parser::ParserConfig, template::DynGenerator};
+use super::{diagnostics::SourceSpan, parser::ParserConfig, template::DynGenerator};
 
 pub(super) trait Generator: Debug {
     /* Generates a String from a Directive. */
     fn generate(&self, _: &LuaContext) -> Result<String>;
 
+    // Collects every `include` path reachable from this node, so a cache
+    // key can fold in the content of whatever a template transitively
+    // includes (see `cache::hash_include`). Default is a no-op; directives
+    // with child blocks recurse into them, and `Include` adds its own path.
+    fn collect_include_paths(&self, _out: &mut Vec<String>) {}
+
     // NOTE: Might be sensible to put this method in ParserConfig and possibly add another trait?
     // idk lets keep it simple for now
     fn _display(&self, _: ParserConfig) -> Result<String> {
@@ -41,47 +51,178 @@ impl Generator for &str {
 
 #[derive(Debug, Clone)]
 pub(super) struct If {
+    pub span: SourceSpan,
     pub condition: String,
     pub blocks: Vec<Rc<dyn Generator>>,
 }
 
 impl Generator for If {
     fn generate(&self, lua_context: &LuaContext) -> Result<String> {
-        let condition_result = lua_context.load(&self.condition).eval::<bool>()?;
+        let condition_result = lua_context
+            .load(&self.condition)
+            .eval::<bool>()
+            .map_err(|e| super::diagnostics::annotate(&self.span, e))?;
         if condition_result {
             self.blocks.generate(lua_context)
         } else {
             Ok("".to_string())
         }
     }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        self.blocks.collect_include_paths(out);
+    }
 }
 
+// Deliberately not the nested-else shape (`{ condition, if_blocks,
+// else_blocks }` with `elseif` chained as a nested `IfElse`): flattening
+// every branch into one `Vec` keeps `generate` a single loop instead of a
+// recursion that gets one `SourceSpan` per nesting level confused with the
+// next. No other code builds an `IfElse` outside this module's parser, so
+// nothing depends on the old nested-else field names.
 #[derive(Debug, Clone)]
 pub(super) struct IfElse {
-    pub condition: String,
-    pub if_blocks: Vec<Rc<dyn Generator>>,
+    // (span, condition, blocks) triples in source order: the `if` branch
+    // followed by zero or more `elseif` branches. The first one whose
+    // condition evaluates truthy wins.
+    pub branches: Vec<(SourceSpan, String, Vec<Rc<dyn Generator>>)>,
     pub else_blocks: Vec<Rc<dyn Generator>>,
 }
 
 impl Generator for IfElse {
-    fn generate(&self, _: &LuaContext) -> Result<String> {
-        todo!()
+    fn generate(&self, lua_context: &LuaContext) -> Result<String> {
+        for (span, condition, blocks) in &self.branches {
+            let condition_result = lua_context
+                .load(condition)
+                .eval::<bool>()
+                .map_err(|e| super::diagnostics::annotate(span, e))?;
+            if condition_result {
+                return blocks.generate(lua_context);
+            }
+        }
+        self.else_blocks.generate(lua_context)
+    }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        for (_, _, blocks) in &self.branches {
+            blocks.collect_include_paths(out);
+        }
+        self.else_blocks.collect_include_paths(out);
     }
 }
 
+#[derive(Debug, Clone)]
+pub(super) struct ForEach {
+    pub span: SourceSpan,
+    pub item_name: String,
+    pub collection: String,
+    pub blocks: Vec<Rc<dyn Generator>>,
+}
+
+impl Generator for ForEach {
+    fn generate(&self, lua_context: &LuaContext) -> Result<String> {
+        let collection: LuaTable = lua_context
+            .load(&self.collection)
+            .eval()
+            .map_err(|e| super::diagnostics::annotate(&self.span, e))?;
+        let mut result = String::new();
+        for item in collection.sequence_values::<LuaValue>() {
+            lua_context.globals().set(self.item_name.clone(), item?)?;
+            result.push_str(&self.blocks.generate(lua_context)?);
+        }
+        lua_context.globals().set(self.item_name.clone(), LuaNil)?;
+        Ok(result)
+    }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        self.blocks.collect_include_paths(out);
+    }
+}
+
+thread_local! {
+    // Canonical, absolute paths currently being rendered on this thread, so
+    // `Include` can refuse `a` includes `b` includes `a` instead of
+    // recursing forever, even when the two includes spell the path
+    // differently (`./x.tpl` vs `x.tpl` vs an absolute path).
+    static INCLUDE_STACK: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+    // Directory of each template currently being rendered, innermost last,
+    // so a nested `Include::generate` resolves `self.path` relative to the
+    // file that contains it rather than the process's CWD.
+    static BASE_DIR_STACK: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+}
+
+// Pushes `dir` as the current include-resolution directory for the
+// duration of `f`, then pops it. Nested `Include`s push/pop their own
+// directory as they render, so this is only needed once, by whoever is
+// rendering the root template, to make its top-level `include` paths
+// resolve the same way `Cached::fingerprint` already resolves them (both
+// relative to the root template's own directory rather than the
+// process's CWD).
+pub(super) fn with_base_dir<R>(dir: &Path, f: impl FnOnce() -> R) -> R {
+    BASE_DIR_STACK.with(|stack| stack.borrow_mut().push(dir.to_path_buf()));
+    let result = f();
+    BASE_DIR_STACK.with(|stack| {
+        stack.borrow_mut().pop();
+    });
+    result
+}
+
 #[derive(Debug, Clone)]
 pub(super) struct Include {
+    pub span: SourceSpan,
     pub path: String,
 }
 
 impl Generator for Include {
-    fn generate(&self, _: &LuaContext) -> Result<String> {
-        todo!()
+    fn generate(&self, lua_context: &LuaContext) -> Result<String> {
+        let base_dir = BASE_DIR_STACK
+            .with(|stack| stack.borrow().last().cloned())
+            .unwrap_or_else(|| PathBuf::from("."));
+        let path = base_dir.join(&self.path);
+        let canonical = fs::canonicalize(&path)
+            .with_context(|| format!("failed to resolve included template `{}`", self.path))?;
+
+        let is_cycle = INCLUDE_STACK.with(|stack| !stack.borrow_mut().insert(canonical.clone()));
+        if is_cycle {
+            return Err(super::diagnostics::annotate(
+                &self.span,
+                format!(
+                    "include cycle detected: `{}` is already being rendered",
+                    self.path
+                ),
+            ));
+        }
+
+        let result = (|| {
+            let raw = fs::read_to_string(&canonical)
+                .with_context(|| format!("failed to read included template `{}`", self.path))?;
+            let include_dir = canonical
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("."));
+            BASE_DIR_STACK.with(|stack| stack.borrow_mut().push(include_dir));
+            let rendered = super::parser::parse_template(&raw).and_then(|t| t.generate(lua_context));
+            BASE_DIR_STACK.with(|stack| {
+                stack.borrow_mut().pop();
+            });
+            rendered
+        })();
+
+        INCLUDE_STACK.with(|stack| {
+            stack.borrow_mut().remove(&canonical);
+        });
+
+        result.map_err(|e| super::diagnostics::annotate(&self.span, e))
+    }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        out.push(self.path.clone());
     }
 }
 
 #[derive(Debug, Clone)]
 pub(super) struct Transform {
+    pub span: SourceSpan,
     pub input_name: String,
     pub transform: String,
     pub blocks: Vec<Rc<dyn Generator>>,
@@ -91,10 +232,17 @@ impl Generator for Transform {
     fn generate(&self, lua_context: &LuaContext) -> Result<String> {
         let blocks = self.blocks.generate(lua_context)?;
         lua_context.globals().set(self.input_name.clone(), blocks)?;
-        let r = lua_context.load(&self.transform).eval::<String>()?;
+        let r = lua_context
+            .load(&self.transform)
+            .eval::<String>()
+            .map_err(|e| super::diagnostics::annotate(&self.span, e))?;
         lua_context.globals().set(self.input_name.clone(), LuaNil)?;
         Ok(r)
     }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        self.blocks.collect_include_paths(out);
+    }
 }
 
 impl Generator for Vec<DynGenerator> {
@@ -105,4 +253,108 @@ impl Generator for Vec<DynGenerator> {
         }
         Ok(result.to_string())
     }
+
+    fn collect_include_paths(&self, out: &mut Vec<String>) {
+        for block in self {
+            block.collect_include_paths(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rlua::Lua;
+
+    fn unique_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("templar-directive-test-{}-{}-{}", std::process::id(), name, n));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_include_cycle_is_rejected_instead_of_recursing_forever() {
+        let dir = unique_dir("cycle");
+        fs::write(dir.join("a.tpl"), "!!% include b.tpl \n%!!").unwrap();
+        fs::write(dir.join("b.tpl"), "!!% include a.tpl \n%!!").unwrap();
+
+        let raw = fs::read_to_string(dir.join("a.tpl")).unwrap();
+        let template = super::super::parser::parse_template(&raw).unwrap();
+
+        let lua = Lua::new();
+        let result = with_base_dir(&dir, || lua.context(|lua_context| template.generate(&lua_context)));
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("include cycle"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_nested_include_renders_the_included_file() {
+        let dir = fs::canonicalize(unique_dir("nested")).unwrap();
+        fs::write(dir.join("outer.tpl"), "before !!% include inner.tpl \n%!! after").unwrap();
+        fs::write(dir.join("inner.tpl"), "middle").unwrap();
+
+        let raw = fs::read_to_string(dir.join("outer.tpl")).unwrap();
+        let template = super::super::parser::parse_template(&raw).unwrap();
+
+        let lua = Lua::new();
+        let output = with_base_dir(&dir, || lua.context(|lua_context| template.generate(&lua_context))).unwrap();
+
+        // Each text block is trimmed independently by the parser (see
+        // `parser::text`), so the surrounding whitespace around the
+        // directive doesn't survive into the rendered output.
+        assert_eq!(output, "beforemiddleafter");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    // A block whose `generate` reads back the current value of the `item`
+    // global, so the test can observe exactly what `ForEach::generate` bound
+    // on each pass (and cleared afterward) without a real template to parse.
+    #[derive(Debug)]
+    struct ReadItem;
+
+    impl Generator for ReadItem {
+        fn generate(&self, lua_context: &LuaContext) -> Result<String> {
+            let value: LuaValue = lua_context.globals().get("item")?;
+            Ok(match value {
+                LuaValue::Integer(i) => i.to_string(),
+                other => format!("{:?}", other),
+            })
+        }
+    }
+
+    fn dummy_span() -> SourceSpan {
+        let raw = "!!% foreach item in items \n%!!";
+        let span = super::super::diagnostics::Span::new_extra(raw, Rc::from(raw));
+        SourceSpan::of(&span)
+    }
+
+    #[test]
+    fn test_foreach_binds_item_for_each_pass_and_restores_nil_after() {
+        let foreach = ForEach {
+            span: dummy_span(),
+            item_name: "item".to_string(),
+            collection: "{1, 2, 3}".to_string(),
+            blocks: vec![Rc::new(ReadItem) as Rc<dyn Generator>],
+        };
+
+        let lua = Lua::new();
+        let output = lua.context(|lua_context| foreach.generate(&lua_context)).unwrap();
+        assert_eq!(output, "123");
+
+        let cleared = lua.context(|lua_context| {
+            matches!(
+                lua_context.globals().get::<_, LuaValue>("item").unwrap(),
+                LuaValue::Nil
+            )
+        });
+        assert!(cleared);
+    }
 }
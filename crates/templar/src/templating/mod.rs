@@ -0,0 +1,12 @@
+mod cache;
+mod context;
+mod diagnostics;
+mod directive;
+mod parser;
+mod template;
+
+pub(crate) use cache::{Cached, TemplateCache};
+pub(crate) use context::{TemplateContext, Value as ContextValue};
+pub(crate) use directive::with_base_dir;
+pub(crate) use parser::parse_template;
+pub(crate) use template::Template;
@@ -0,0 +1,404 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag},
+    character::complete::char,
+    combinator::map,
+    multi::many0,
+    sequence::{preceded, terminated},
+    IResult,
+};
+
+use super::{
+    diagnostics::{SourceSpan, Span},
+    directive::{ForEach, If, IfElse, Include, Transform},
+    template::{DynGenerator, Template},
+};
+
+thread_local! {
+    // The most recent directive-dispatch error message (`unknown directive
+    // ...`, `` `include` directive requires a path``, ...), stashed by
+    // `directive_block` because nom's `IResult` error type can't carry an
+    // owned `String`. `parse_template` prefers this over nom's generic
+    // failure text when it's set.
+    static LAST_DIRECTIVE_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+// Parses a raw template string into a Template
+pub(super) fn parse_template(raw_template: &str) -> Result<Template> {
+    let source: Rc<str> = Rc::from(raw_template);
+    let input = Span::new_extra(raw_template, source.clone());
+    match template(input) {
+        Ok((_, blocks)) => Ok(Template { blocks, source }),
+        Err(e) => match LAST_DIRECTIVE_ERROR.with(|last| last.borrow_mut().take()) {
+            Some(message) => anyhow::bail!("{}", message),
+            None => anyhow::bail!("{}", e), // Rethrow the error (lifetimes stuff)
+        },
+    }
+}
+
+// Knobs the parser is driven with. Split out of the free functions below so
+// callers (and `Generator::_display`) can eventually ask "how would this
+// directive have been written back out" without hardcoding the marks twice.
+#[derive(Debug, Clone)]
+pub(super) struct ParserConfig {
+    pub opening_mark: String,
+    pub closing_mark: String,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            opening_mark: OPENING_MARK.to_string(),
+            closing_mark: CLOSING_MARK.to_string(),
+        }
+    }
+}
+
+// PARSER CODE
+
+const OPENING_MARK: &str = "!!%";
+const CLOSING_MARK: &str = "%!!";
+
+/*
+ * text    Text generator
+ * ( ... ) directive generator, possibly nesting more of the same
+ * text    Text generator
+ */
+fn template(input: Span) -> IResult<Span, Vec<DynGenerator>> {
+    many0(alt((directive_block, text)))(input)
+}
+
+fn text(input: Span) -> IResult<Span, DynGenerator> {
+    map(is_not(OPENING_MARK), |t: Span| {
+        Rc::new(t.fragment().trim().to_string()) as DynGenerator
+    })(input)
+}
+
+/*
+ * text
+ * directive_block
+ */
+fn template_block(input: Span) -> IResult<Span, DynGenerator> {
+    alt((
+        directive_block,
+        map(is_not(CLOSING_MARK), |t: Span| {
+            Rc::new(t.fragment().trim().to_string()) as DynGenerator
+        }),
+    ))(input)
+}
+
+/*
+ * ( header template_blocks )
+ *
+ * `if` is special-cased: its body can contain sibling `elseif`/`else`
+ * markers that belong to the same directive rather than to a nested one, so
+ * it can't be parsed with the plain `many0(template_block)` every other
+ * directive uses.
+ */
+fn directive_block(input: Span) -> IResult<Span, DynGenerator> {
+    let directive_span = SourceSpan::of(&input);
+    let error_input = input.clone();
+    let (after_header, header) = preceded(tag(OPENING_MARK), directive_header)(input)?;
+    let mut words = header.splitn(2, char::is_whitespace);
+    let keyword = words.next().unwrap_or_default();
+    let args = words.next().unwrap_or_default().trim();
+
+    let (rest, blocks_result) = if keyword == "if" {
+        let (rest, branches) = parse_if_branches(after_header)?;
+        (rest, build_if(args, directive_span, branches))
+    } else {
+        let (rest, blocks) = many0(template_block)(after_header)?;
+        (rest, dispatch_directive(keyword, args, blocks, directive_span))
+    };
+
+    let (rest, _) = tag(CLOSING_MARK)(rest)?;
+    let directive = blocks_result.map_err(|e| {
+        LAST_DIRECTIVE_ERROR.with(|last| *last.borrow_mut() = Some(e.to_string()));
+        nom::Err::Failure(nom::error::Error::new(error_input, nom::error::ErrorKind::Verify))
+    })?;
+
+    Ok((rest, directive))
+}
+
+fn directive_header(input: Span) -> IResult<Span, &str> {
+    terminated(map(is_not("\n"), |t: Span| t.fragment().trim()), char('\n'))(input)
+}
+
+type IfBranches = (
+    Vec<DynGenerator>,
+    Vec<(SourceSpan, String, Vec<DynGenerator>)>,
+    Vec<DynGenerator>,
+);
+
+// Consumes template blocks up to (but not including) the directive's final
+// closing mark, splitting them at any `elseif`/`else` separators found along
+// the way. Returns (if_blocks, elseif_branches, else_blocks).
+fn parse_if_branches(mut input: Span) -> IResult<Span, IfBranches> {
+    let mut if_blocks = Vec::new();
+    let mut elseif_branches: Vec<(SourceSpan, String, Vec<DynGenerator>)> = Vec::new();
+    let mut else_blocks = Vec::new();
+    let mut in_else = false;
+
+    loop {
+        let branch_span = SourceSpan::of(&input);
+        if let Ok((rest, header)) = branch_separator(input.clone()) {
+            if in_else {
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::Verify,
+                )));
+            }
+            match header.strip_prefix("elseif") {
+                Some(condition) => {
+                    elseif_branches.push((branch_span, condition.trim().to_string(), Vec::new()))
+                }
+                None => in_else = true, // header == "else"
+            }
+            input = rest;
+            continue;
+        }
+
+        match template_block(input.clone()) {
+            Ok((rest, block)) => {
+                match (in_else, elseif_branches.last_mut()) {
+                    (true, _) => else_blocks.push(block),
+                    (false, Some((_, _, blocks))) => blocks.push(block),
+                    (false, None) => if_blocks.push(block),
+                }
+                input = rest;
+            }
+            // A `Failure` means a nested directive was recognized but is
+            // malformed (e.g. `dispatch_directive` rejected an unknown
+            // keyword) — propagate it instead of treating it the same as a
+            // plain `Error`, or the bad directive and everything after it
+            // silently vanishes from the rendered output instead of erroring
+            // the way a top-level `many0(template_block)` directive would.
+            Err(nom::Err::Failure(e)) => return Err(nom::Err::Failure(e)),
+            Err(_) => break,
+        }
+    }
+
+    Ok((input, (if_blocks, elseif_branches, else_blocks)))
+}
+
+// Recognizes a bare `!!% else` / `!!% elseif <condition>` line without
+// consuming a matching closing mark, since it doesn't own one.
+fn branch_separator(input: Span) -> IResult<Span, &str> {
+    let error_input = input.clone();
+    let (rest, header) = preceded(tag(OPENING_MARK), directive_header)(input)?;
+    if header == "else" || header.starts_with("elseif ") {
+        Ok((rest, header))
+    } else {
+        Err(nom::Err::Error(nom::error::Error::new(
+            error_input,
+            nom::error::ErrorKind::Tag,
+        )))
+    }
+}
+
+fn build_if(
+    condition: &str,
+    span: SourceSpan,
+    (if_blocks, elseif_branches, else_blocks): IfBranches,
+) -> Result<DynGenerator> {
+    if condition.is_empty() {
+        anyhow::bail!("`if` directive requires a condition");
+    }
+    if elseif_branches.is_empty() && else_blocks.is_empty() {
+        return Ok(Rc::new(If {
+            span,
+            condition: condition.to_string(),
+            blocks: if_blocks,
+        }));
+    }
+    let mut branches = vec![(span, condition.to_string(), if_blocks)];
+    branches.extend(elseif_branches);
+    Ok(Rc::new(IfElse {
+        branches,
+        else_blocks,
+    }))
+}
+
+// Turns a (non-`if`) directive's keyword + arguments plus its already-parsed
+// nested blocks into the `Generator` the keyword names.
+fn dispatch_directive(
+    keyword: &str,
+    args: &str,
+    blocks: Vec<DynGenerator>,
+    span: SourceSpan,
+) -> Result<DynGenerator> {
+    match keyword {
+        "include" => {
+            if !blocks.is_empty() {
+                anyhow::bail!("`include` directive does not take a body");
+            }
+            if args.is_empty() {
+                anyhow::bail!("`include` directive requires a path");
+            }
+            Ok(Rc::new(Include {
+                span,
+                path: args.to_string(),
+            }))
+        }
+        "foreach" => {
+            let (item_name, collection) = args.split_once(" in ").ok_or_else(|| {
+                anyhow::anyhow!(
+                    "`foreach` directive expects `<name> in <lua expr>`, got `{}`",
+                    args
+                )
+            })?;
+            Ok(Rc::new(ForEach {
+                span,
+                item_name: item_name.trim().to_string(),
+                collection: collection.trim().to_string(),
+                blocks,
+            }))
+        }
+        "transform" => {
+            let (input_name, transform) = args.split_once(" as ").ok_or_else(|| {
+                anyhow::anyhow!(
+                    "`transform` directive expects `<name> as <lua expr>`, got `{}`",
+                    args
+                )
+            })?;
+            Ok(Rc::new(Transform {
+                span,
+                input_name: input_name.trim().to_string(),
+                transform: transform.trim().to_string(),
+                blocks,
+            }))
+        }
+        other => anyhow::bail!("unknown directive `{}`", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(raw: &str) -> Span {
+        Span::new_extra(raw, Rc::from(raw))
+    }
+
+    #[test]
+    fn test_text_blocks_pass_through() {
+        let input = " just some text ";
+        let (rest, blocks) = template(span(input)).unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(format!("{:?}", blocks[0]), "\"just some text\"");
+    }
+
+    #[test]
+    fn test_if_directive_dispatches() {
+        let input = format!(
+            "before {} if x > 1 \n inside {}after",
+            OPENING_MARK, CLOSING_MARK
+        );
+        let (_, blocks) = template(span(&input)).unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert!(format!("{:?}", blocks[1]).starts_with("If {"));
+    }
+
+    #[test]
+    fn test_unknown_directive_is_an_error() {
+        let input = format!("{} bogus \n inside {}", OPENING_MARK, CLOSING_MARK);
+        assert!(template(span(&input)).is_err());
+    }
+
+    #[test]
+    fn test_ifelse_directive_dispatches() {
+        let input = format!(
+            "{m} if x > 1 \n a {m} elseif x > 0 \n b {m} else \n c {m2}",
+            m = OPENING_MARK,
+            m2 = CLOSING_MARK
+        );
+        let (rest, blocks) = template(span(&input)).unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert_eq!(blocks.len(), 1);
+        let debug = format!("{:?}", blocks[0]);
+        assert!(debug.starts_with("IfElse {"));
+        assert!(debug.contains("x > 0"));
+    }
+
+    #[test]
+    fn test_plain_if_stays_an_if_not_an_ifelse() {
+        let input = format!("{} if x > 1 \n a {}", OPENING_MARK, CLOSING_MARK);
+        let (_, blocks) = template(span(&input)).unwrap();
+        assert!(format!("{:?}", blocks[0]).starts_with("If {"));
+    }
+
+    #[test]
+    fn test_include_directive_dispatches() {
+        let input = format!(
+            "{} include partials/header.tpl \n{}",
+            OPENING_MARK, CLOSING_MARK
+        );
+        let (_, blocks) = template(span(&input)).unwrap();
+        assert_eq!(blocks.len(), 1);
+        let debug = format!("{:?}", blocks[0]);
+        assert!(debug.starts_with("Include {"));
+        assert!(debug.contains("path: \"partials/header.tpl\""));
+    }
+
+    #[test]
+    fn test_include_with_a_body_is_an_error() {
+        let input = format!(
+            "{} include partials/header.tpl \n not allowed {}",
+            OPENING_MARK, CLOSING_MARK
+        );
+        assert!(template(span(&input)).is_err());
+    }
+
+    #[test]
+    fn test_foreach_directive_dispatches() {
+        let input = format!(
+            "{} foreach item in items \n {{item}} {}",
+            OPENING_MARK, CLOSING_MARK
+        );
+        let (_, blocks) = template(span(&input)).unwrap();
+        assert_eq!(blocks.len(), 1);
+        let debug = format!("{:?}", blocks[0]);
+        assert!(debug.starts_with("ForEach {"));
+        assert!(debug.contains("\"item\""));
+        assert!(debug.contains("\"items\""));
+    }
+
+    #[test]
+    fn test_foreach_without_in_is_an_error() {
+        let input = format!("{} foreach item \n body {}", OPENING_MARK, CLOSING_MARK);
+        assert!(template(span(&input)).is_err());
+    }
+
+    #[test]
+    fn test_else_after_else_is_an_error() {
+        let input = format!(
+            "{m} if x > 1 \n a {m} else \n b {m} else \n c {m2}",
+            m = OPENING_MARK,
+            m2 = CLOSING_MARK
+        );
+        assert!(template(span(&input)).is_err());
+    }
+
+    #[test]
+    fn test_unknown_directive_nested_in_if_body_is_an_error() {
+        // A malformed/unknown directive inside an `if` body goes through
+        // `parse_if_branches` instead of the shared `many0(template_block)`
+        // every other directive uses, so it has its own chance to swallow
+        // the `Failure` `dispatch_directive` raises and silently drop the
+        // rest of the template (see `parse_if_branches`'s `Err(_) => break`
+        // fix). This must error, not render as if `bogus` and everything
+        // after the `if` were never there.
+        let input = format!(
+            "before {m} if x > 1 \n{m} bogus \nbody\n{m2}\n{m2} after",
+            m = OPENING_MARK,
+            m2 = CLOSING_MARK
+        );
+        assert!(template(span(&input)).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::Result;
+use rlua::prelude::*;
+
+use super::cache::{Cached, TemplateCache};
+use super::context::TemplateContext;
+use super::directive::Generator;
+
+// A parsed directive (or a block of text) is just a node that knows how to
+// render itself against a Lua context. Keeping this behind an `Rc` lets a
+// cloned directive share its child subtrees instead of deep-copying them.
+pub(super) type DynGenerator = Rc<dyn Generator>;
+
+#[derive(Debug)]
+pub(super) struct Template {
+    pub blocks: Vec<DynGenerator>,
+    // Raw template text, kept around so `Cached::fingerprint` can hash it
+    // without re-serializing the parsed `blocks` tree.
+    pub source: Rc<str>,
+}
+
+impl Template {
+    pub fn generate(&self, lua_context: &LuaContext) -> Result<String> {
+        self.blocks.generate(lua_context)
+    }
+
+    // Every `include` path reachable from this template, used by
+    // `Cached::fingerprint` to fold included files into the cache key.
+    pub(super) fn include_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        self.blocks.collect_include_paths(&mut paths);
+        paths
+    }
+
+    // Same as `generate`, but checks `cache` first and persists a miss under
+    // this template's fingerprint. `inputs` should list whatever Lua-visible
+    // values the caller is about to set as globals, and `base_dir` is the
+    // directory `include` paths resolve against (the directory containing
+    // this template), so a changed input or included file busts the cached
+    // entry instead of silently reusing stale output.
+    //
+    // Caching is whole-template granularity: a hit skips rendering this
+    // `Template` entirely, a miss re-renders every node in it, including any
+    // `Include`/`Transform` that could in principle be cached on its own.
+    // Per-node caching (checking `Cached` before `generate` on an individual
+    // directive, so an unchanged nested `Include` is skipped even when a
+    // sibling changed) is not implemented here.
+    pub fn generate_cached(
+        &self,
+        lua_context: &LuaContext,
+        cache: &TemplateCache,
+        inputs: &[&str],
+        base_dir: &Path,
+    ) -> Result<String> {
+        let fingerprint = self.fingerprint(inputs, base_dir);
+        if let Some(rendered) = cache.get(&fingerprint) {
+            return Ok(rendered);
+        }
+        let rendered = self.generate(lua_context)?;
+        cache.put(&fingerprint, &rendered)?;
+        Ok(rendered)
+    }
+
+    // Injects `context` as Lua globals before rendering, so callers supply
+    // structured data instead of populating `lua_context.globals()` by hand.
+    pub fn generate_with_context(
+        &self,
+        lua_context: &LuaContext,
+        context: &TemplateContext,
+    ) -> Result<String> {
+        context.inject(lua_context)?;
+        self.generate(lua_context)
+    }
+}